@@ -1,21 +1,29 @@
 // Building an agentic RAG workflow
 // Setting up our agent
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::Result;
 use async_openai::types::{
     ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
     ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs,
-    CreateEmbeddingRequest, EmbeddingInput
 };
-use async_openai::Embeddings;
 use async_openai::{config::OpenAIConfig, Client as OpenAIClient};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use notify::RecommendedWatcher;
 use qdrant_client::prelude::{ Payload, PointStruct, QdrantClient };
 use qdrant_client::qdrant::{
-    with_payload_selector::SelectorOptions, SearchPoints, WithPayloadSelector,
+    points_selector::PointsSelectorOneOf, with_payload_selector::SelectorOptions, Condition,
+    CountPoints, Filter, PointsIdsList, PointsSelector, Range, ScrollPoints, SearchPoints, Value,
+    WithPayloadSelector,
 };
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinHandle;
 
-use crate::files::File;
+use crate::embeddings::{EmbeddingProvider, OpenAiEmbedder};
+use crate::files::{Chunk, File};
+use crate::watcher::{self, Change};
 
 static SYSTEM_MESSAGE: &str = "
     You are a world-class data analyst, specialising in analysing comma-delimited CSV files.
@@ -26,15 +34,42 @@ static SYSTEM_MESSAGE: &str = "
     ";
 
 static COLLECTION: &str = "my-collection";
+static CACHE_COLLECTION: &str = "my-collection-cache";
 
-// text-embedding-ada-002 is the model name from OpenAI that deals with embeddings
-static EMBED_MODEL: &str = "text-embedding-ada-002";
 static PROMPT_MODEL: &str = "gpt-4o";
 
+// Cosine similarity a cached query must clear before we trust its answer for a new prompt.
+const DEFAULT_CACHE_SIMILARITY_THRESHOLD: f32 = 0.95;
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+const DEFAULT_CACHE_MAX_SIZE: u64 = 10_000;
+// How many cache entries are scanned to find the oldest one when evicting to make room.
+const CACHE_EVICTION_SCAN_LIMIT: u32 = 1000;
+
+// How many candidates each of the dense/keyword lists contributes before Reciprocal Rank
+// Fusion, and the `k` constant in RRF's `1 / (k + rank)` score.
+const DEFAULT_HYBRID_SEARCH_LIMIT: u64 = 10;
+const DEFAULT_RRF_K: u32 = 60;
+
+// How many points the keyword pass scans per query. A full scroll over the whole collection
+// doesn't scale, so this caps the cost of the keyword pass; points past the cap are not searched.
+const KEYWORD_SCAN_LIMIT: u32 = 1000;
+
+// How many chunks go into a single embedding request / Qdrant upsert call, and how many
+// embedding requests are allowed to be in flight at once.
+const EMBED_BATCH_SIZE: usize = 100;
+const EMBED_CONCURRENCY: usize = 4;
+const UPSERT_BATCH_SIZE: usize = 100;
+
 #[derive(Clone)]
 pub struct MyAgent {
     openai_client: OpenAIClient<OpenAIConfig>,
     qdrant_client: Arc<QdrantClient>,
+    embedding_provider: Arc<dyn EmbeddingProvider>,
+    cache_similarity_threshold: f32,
+    cache_ttl: Duration,
+    cache_max_size: u64,
+    hybrid_search_limit: u64,
+    rrf_k: u32,
 }
 
 impl MyAgent {
@@ -43,10 +78,27 @@ impl MyAgent {
         let config = OpenAIConfig::new().with_api_key(api_key);
 
         let openai_client = OpenAIClient::with_config(config);
+        let embedding_provider = Arc::new(OpenAiEmbedder::new(openai_client.clone()));
+
+        Self::with_embedding_provider(qdrant_client, openai_client, embedding_provider)
+    }
 
+    // Lets callers swap in a different `EmbeddingProvider`, e.g. `OllamaEmbedder`, for fully
+    // local/self-hosted embeddings instead of OpenAI's.
+    pub fn with_embedding_provider(
+        qdrant_client: QdrantClient,
+        openai_client: OpenAIClient<OpenAIConfig>,
+        embedding_provider: Arc<dyn EmbeddingProvider>,
+    ) -> Self {
         Self {
             openai_client,
             qdrant_client: Arc::new(qdrant_client),
+            embedding_provider,
+            cache_similarity_threshold: DEFAULT_CACHE_SIMILARITY_THRESHOLD,
+            cache_ttl: DEFAULT_CACHE_TTL,
+            cache_max_size: DEFAULT_CACHE_MAX_SIZE,
+            hybrid_search_limit: DEFAULT_HYBRID_SEARCH_LIMIT,
+            rrf_k: DEFAULT_RRF_K,
         }
     }
     /*
@@ -55,8 +107,40 @@ impl MyAgent {
     }
     */
 
+    // Overrides the cosine similarity threshold, TTL and max size for the semantic answer
+    // cache, which otherwise default to `DEFAULT_CACHE_SIMILARITY_THRESHOLD`,
+    // `DEFAULT_CACHE_TTL` and `DEFAULT_CACHE_MAX_SIZE`.
+    pub fn with_cache_config(mut self, similarity_threshold: f32, ttl: Duration, max_size: u64) -> Self {
+        self.cache_similarity_threshold = similarity_threshold;
+        self.cache_ttl = ttl;
+        self.cache_max_size = max_size;
+        self
+    }
+
+    // Overrides how many candidates each of the dense/keyword lists contributes (`N`) and the
+    // `k` constant used by Reciprocal Rank Fusion, which otherwise default to
+    // `DEFAULT_HYBRID_SEARCH_LIMIT` and `DEFAULT_RRF_K`.
+    pub fn with_hybrid_search_params(mut self, hybrid_search_limit: u64, rrf_k: u32) -> Self {
+        self.hybrid_search_limit = hybrid_search_limit;
+        self.rrf_k = rrf_k;
+        self
+    }
+
+    // Vector size of whichever `EmbeddingProvider` this agent was built with, for sizing the
+    // Qdrant collections at creation time.
+    pub fn embedding_dimensions(&self) -> u64 {
+        self.embedding_provider.dimensions()
+    }
+
     pub async fn prompt(&self, prompt: &str) -> anyhow::Result<String> {
-        let context = self.search_document(prompt.to_owned()).await?;
+        let embedding = self.embed_query(prompt.to_owned()).await?;
+
+        if let Some(cached) = self.search_cache(&embedding).await? {
+            println!("Returning cached answer for prompt: {prompt}");
+            return Ok(cached);
+        }
+
+        let context = self.search_document(&embedding, prompt).await?;
         let input = format!(
             "{prompt}
             Provided context:
@@ -92,21 +176,194 @@ impl MyAgent {
 
         println!("Retrieved result from prompt: {res}");
 
+        self.cache_answer(prompt, &embedding, &res).await?;
+
         Ok(res)
     }
 
-    async fn search_document(&self, prompt: String) -> Result<String> {
-        let request = CreateEmbeddingRequest {
-            model: EMBED_MODEL.to_string(),
-            input: EmbeddingInput::String(prompt),
-            user: None,
+    async fn embed_query(&self, prompt: String) -> Result<Vec<f32>> {
+        let mut embeddings = self.embedding_provider.embed(vec![prompt]).await?;
+
+        Ok(embeddings.remove(0))
+    }
+
+    // Looks up `embedding` in the semantic cache, returning the cached answer only if it's
+    // both similar enough and still within `cache_ttl`.
+    async fn search_cache(&self, embedding: &[f32]) -> Result<Option<String>> {
+        let payload_selector = WithPayloadSelector {
+            selector_options: Some(SelectorOptions::Enable(true)),
+        };
+
+        let search_points = SearchPoints {
+            collection_name: CACHE_COLLECTION.to_string(),
+            vector: embedding.to_owned(),
+            limit: 1,
+            with_payload: Some(payload_selector),
             ..Default::default()
         };
 
-        let embeddings_result = Embeddings::new(&self.openai_client).create(request).await?;
+        let search_result = self.qdrant_client.search_points(&search_points).await?;
+
+        let hit = match search_result.result.into_iter().next() {
+            Some(hit) if hit.score >= self.cache_similarity_threshold => hit,
+            _ => return Ok(None),
+        };
+
+        let cached_at = hit
+            .payload
+            .get("cached_at")
+            .and_then(|v| v.as_integer())
+            .unwrap_or(0) as u64;
+        let age = now_unix().saturating_sub(cached_at);
+
+        if age > self.cache_ttl.as_secs() {
+            return Ok(None);
+        }
+
+        // `Value`'s `Display` wraps/escapes stored strings (quotes included), which would make
+        // a cache hit look different from the raw string the fresh path returns - pull the
+        // inner string out instead.
+        Ok(hit
+            .payload
+            .get("answer")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string()))
+    }
+
+    // Stores `answer` under `embedding` in the cache collection. Expired entries are swept out
+    // first; if the cache is still at `cache_max_size` afterwards, one entry is evicted to make
+    // room, so the cache recycles rather than permanently refusing new answers once it fills up.
+    // The evicted entry is the oldest among the first `CACHE_EVICTION_SCAN_LIMIT` scanned, which
+    // is not necessarily the oldest overall when the cache holds more entries than that.
+    async fn cache_answer(&self, original_query: &str, embedding: &[f32], answer: &str) -> Result<()> {
+        self.evict_expired_cache_entries().await?;
+
+        if self.cache_size().await? >= self.cache_max_size {
+            self.evict_oldest_cache_entry().await?;
+        }
+
+        let payload: Payload = serde_json::json!({
+            "answer": answer,
+            "original_query": original_query,
+            "cached_at": now_unix(),
+        })
+        .try_into()
+        .unwrap();
+
+        let point = PointStruct::new(uuid::Uuid::new_v4().to_string(), embedding.to_owned(), payload);
 
-        let embedding = &embeddings_result.data.first().unwrap().embedding;
+        self.qdrant_client
+            .upsert_points(CACHE_COLLECTION, None, vec![point], None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn cache_size(&self) -> Result<u64> {
+        let count = self
+            .qdrant_client
+            .count(CountPoints {
+                collection_name: CACHE_COLLECTION.to_string(),
+                ..Default::default()
+            })
+            .await?
+            .result
+            .map(|r| r.count)
+            .unwrap_or(0);
 
+        Ok(count)
+    }
+
+    async fn evict_expired_cache_entries(&self) -> Result<()> {
+        let cutoff = now_unix().saturating_sub(self.cache_ttl.as_secs()) as f64;
+
+        let filter = Filter {
+            must: vec![Condition::range(
+                "cached_at",
+                Range {
+                    lt: Some(cutoff),
+                    ..Default::default()
+                },
+            )],
+            ..Default::default()
+        };
+
+        self.qdrant_client
+            .delete_points(CACHE_COLLECTION, None, &filter.into(), None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn evict_oldest_cache_entry(&self) -> Result<()> {
+        let payload_selector = WithPayloadSelector {
+            selector_options: Some(SelectorOptions::Enable(true)),
+        };
+
+        let scroll_result = self
+            .qdrant_client
+            .scroll(ScrollPoints {
+                collection_name: CACHE_COLLECTION.to_string(),
+                with_payload: Some(payload_selector),
+                limit: Some(CACHE_EVICTION_SCAN_LIMIT),
+                ..Default::default()
+            })
+            .await?;
+
+        let oldest_id = scroll_result
+            .result
+            .into_iter()
+            .filter_map(|point| {
+                let cached_at = point.payload.get("cached_at")?.as_integer()?;
+                Some((cached_at, point.id?))
+            })
+            .min_by_key(|(cached_at, _)| *cached_at)
+            .map(|(_, id)| id);
+
+        let Some(id) = oldest_id else {
+            return Ok(());
+        };
+
+        let selector = PointsSelector {
+            points_selector_one_of: Some(PointsSelectorOneOf::Points(PointsIdsList { ids: vec![id] })),
+        };
+
+        self.qdrant_client
+            .delete_points(CACHE_COLLECTION, None, &selector, None)
+            .await?;
+
+        Ok(())
+    }
+
+    // Hybrid retrieval: fuse the dense vector ranking with a keyword ranking via Reciprocal
+    // Rank Fusion, falling back to pure vector search if the keyword pass can't run.
+    async fn search_document(&self, embedding: &[f32], query: &str) -> Result<String> {
+        let dense_ranked = self.dense_search(embedding).await?;
+
+        let fused = match self.keyword_search(query).await {
+            Ok(keyword_ranked) if !keyword_ranked.is_empty() => reciprocal_rank_fusion(
+                &dense_ranked,
+                &keyword_ranked,
+                self.rrf_k,
+                self.hybrid_search_limit as usize,
+            ),
+            _ => dense_ranked,
+        };
+
+        if fused.is_empty() {
+            return Err(anyhow::anyhow!("There were no results that matched :("));
+        }
+
+        let context = fused
+            .iter()
+            .filter_map(|hit| hit.payload.get("contents").map(|v| v.to_string()))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        Ok(context)
+    }
+
+    async fn dense_search(&self, embedding: &[f32]) -> Result<Vec<RankedHit>> {
         let payload_selector = WithPayloadSelector {
             selector_options: Some(SelectorOptions::Enable(true)),
         };
@@ -114,57 +371,372 @@ impl MyAgent {
         let search_points = SearchPoints {
             collection_name: COLLECTION.to_string(),
             vector: embedding.to_owned(),
-            limit: 1,
+            limit: self.hybrid_search_limit,
             with_payload: Some(payload_selector),
             ..Default::default()
         };
 
         let search_result = self.qdrant_client.search_points(&search_points).await?;
-        let result = search_result.result.into_iter().next();
 
-        match result {
-            Some(res) => Ok(res.payload.get("contents").unwrap().to_string()),
-            None => Err(anyhow::anyhow!("There were no results that matched :(")),
+        Ok(search_result
+            .result
+            .into_iter()
+            .map(|hit| RankedHit {
+                payload: hit.payload,
+            })
+            .collect())
+    }
+
+    // Scores up to `KEYWORD_SCAN_LIMIT` points by how many query terms their `contents` payload
+    // contains; points beyond that scan are not searched. This is a
+    // stand-in for a proper sparse/full-text index; if the scroll itself fails (index
+    // unavailable, collection not reachable), the caller falls back to dense-only search.
+    async fn keyword_search(&self, query: &str) -> Result<Vec<RankedHit>> {
+        let terms: Vec<String> = query
+            .split_whitespace()
+            .map(|term| term.to_lowercase())
+            .collect();
+
+        if terms.is_empty() {
+            return Ok(Vec::new());
         }
+
+        let payload_selector = WithPayloadSelector {
+            selector_options: Some(SelectorOptions::Enable(true)),
+        };
+
+        let scroll_result = self
+            .qdrant_client
+            .scroll(ScrollPoints {
+                collection_name: COLLECTION.to_string(),
+                with_payload: Some(payload_selector),
+                limit: Some(KEYWORD_SCAN_LIMIT),
+                ..Default::default()
+            })
+            .await?;
+
+        let mut scored: Vec<(usize, RankedHit)> = scroll_result
+            .result
+            .into_iter()
+            .filter_map(|point| {
+                let haystack = point.payload.get("contents")?.to_string().to_lowercase();
+                let score = terms
+                    .iter()
+                    .filter(|term| haystack.contains(term.as_str()))
+                    .count();
+
+                if score == 0 {
+                    return None;
+                }
+
+                Some((score, RankedHit { payload: point.payload }))
+            })
+            .collect();
+
+        scored.sort_by(|a, b| b.0.cmp(&a.0));
+        scored.truncate(self.hybrid_search_limit as usize);
+
+        Ok(scored.into_iter().map(|(_, hit)| hit).collect())
     }
 
+    // Streams `file.chunks` through embedding in batches of `EMBED_BATCH_SIZE`, running up to
+    // `EMBED_CONCURRENCY` embedding requests concurrently, then upserts the resulting points to
+    // Qdrant in bulk batches of `UPSERT_BATCH_SIZE`. This is what lets a large CSV (tens of
+    // thousands of rows) ingest without a single oversized request timing out.
     pub async fn embed_document(&self, file: File) -> Result<()> {
-        if file.rows.is_empty() {
+        if file.chunks.is_empty() {
             return Err(anyhow::anyhow!("There's no rows to embed!"));
         }
 
-        let request = CreateEmbeddingRequest {
-            model: EMBED_MODEL.to_string(),
-            input: EmbeddingInput::StringArray(file.rows.clone()),
-            user: None,
-            dimensions: Some(1536),
-            ..Default::default()
-        };
+        let semaphore = Arc::new(Semaphore::new(EMBED_CONCURRENCY));
 
-        let embedding_result = Embeddings::new(&self.openai_client).create(request).await?;
+        let points: Vec<PointStruct> = stream::iter(file.chunks.chunks(EMBED_BATCH_SIZE).map(<[Chunk]>::to_vec))
+            .map(|batch| {
+                let semaphore = Arc::clone(&semaphore);
+                let embedding_provider = Arc::clone(&self.embedding_provider);
 
-        for embedding in embedding_result.data {
-            let payload: Payload = serde_json::json!({
-                "id": file.path.clone(),
-                "content": file.contents,
-                "rows": file.rows
-            })
-            .try_into()
-            .unwrap();
+                async move {
+                    let _permit = semaphore.acquire_owned().await?;
+
+                    let texts = batch.iter().map(|chunk| chunk.contents.clone()).collect();
+                    let embeddings = embedding_provider.embed(texts).await?;
 
-            println!("Embedded: {}", file.path);
+                    let points: Vec<PointStruct> = batch
+                        .into_iter()
+                        .zip(embeddings)
+                        .map(|(chunk, embedding)| chunk_to_point(&chunk, embedding))
+                        .collect();
 
-            let vec = embedding.embedding;
+                    Ok::<Vec<PointStruct>, anyhow::Error>(points)
+                }
+            })
+            .buffer_unordered(EMBED_CONCURRENCY)
+            .try_collect::<Vec<Vec<PointStruct>>>()
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        println!("Embedded {} chunk(s) from {}", points.len(), file.path);
 
-            let points = vec![PointStruct::new(
-                uuid::Uuid::new_v4().to_string(),
-                vec,
-                payload,
-            )];
+        for batch in points.chunks(UPSERT_BATCH_SIZE) {
             self.qdrant_client
-                .upsert_points(COLLECTION, None, points, None)
+                .upsert_points(COLLECTION, None, batch.to_vec(), None)
                 .await?;
         }
+
+        Ok(())
+    }
+
+    // Watches `path` for file changes and keeps the Qdrant collection in sync: new/modified
+    // files are re-embedded and removed files have their stale points deleted, so the knowledge
+    // base self-updates instead of accumulating orphaned or duplicate vectors. Ingestion runs
+    // on a background task, decoupled from the web request path, and changes are drained one at
+    // a time so two changes to the same file can't race each other.
+    pub fn watch(&self, path: impl AsRef<std::path::Path>) -> Result<WatchHandle> {
+        let (notify_watcher, notify_rx) = watcher::start_watching(path.as_ref())?;
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<Change>();
+
+        // `notify` delivers events on its own thread via a std channel; forward them onto the
+        // async queue that the worker task below drains sequentially.
+        std::thread::spawn(move || {
+            for event in notify_rx {
+                let Ok(event) = event else { continue };
+
+                for change in watcher::changes_from_event(event) {
+                    if tx.send(change).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        let agent = self.clone();
+        let task = tokio::spawn(async move {
+            while let Some(change) = rx.recv().await {
+                if let Err(err) = agent.apply_change(change).await {
+                    eprintln!("Failed to sync watched file: {err:#}");
+                }
+            }
+        });
+
+        Ok(WatchHandle {
+            _watcher: notify_watcher,
+            task,
+        })
+    }
+
+    async fn apply_change(&self, change: Change) -> Result<()> {
+        match change {
+            Change::Upsert(path) => {
+                let path_str = path.display().to_string();
+                self.delete_by_path(&path_str).await?;
+                self.embed_document(File::new(path)?).await?;
+            }
+            Change::Remove(path) => self.delete_by_path(&path.display().to_string()).await?,
+        }
+
+        // A cached answer may have been derived from whatever just changed or disappeared, and
+        // the cache has no record of which document(s) backed it, so invalidate it wholesale
+        // rather than keep serving stale answers for up to `cache_ttl`.
+        self.invalidate_cache().await
+    }
+
+    // Deletes every point whose `path` payload field matches, so re-embedding a changed file
+    // doesn't leave its old chunks behind as orphaned/duplicate vectors.
+    async fn delete_by_path(&self, path: &str) -> Result<()> {
+        let filter = Filter {
+            must: vec![Condition::matches("path", path.to_string())],
+            ..Default::default()
+        };
+
+        self.qdrant_client
+            .delete_points(COLLECTION, None, &filter.into(), None)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn invalidate_cache(&self) -> Result<()> {
+        self.qdrant_client
+            .delete_points(CACHE_COLLECTION, None, &Filter::default().into(), None)
+            .await?;
+
         Ok(())
     }
+}
+
+// Handle to a running `MyAgent::watch` background task. Call `stop()` to abort the task at once.
+// Dropping the handle drops the `notify` watcher, which closes the event channel: the task then
+// finishes the changes already queued and exits.
+pub struct WatchHandle {
+    _watcher: RecommendedWatcher,
+    task: JoinHandle<()>,
+}
+
+impl WatchHandle {
+    pub fn stop(self) {
+        self.task.abort();
+    }
+}
+
+// A single candidate from either the dense or keyword ranking, identified for fusion purposes
+// by its `path`/`row_start`/`row_end` payload fields rather than the Qdrant point id, since
+// those are what uniquely identify a chunk across the two ranked lists.
+#[derive(Clone)]
+struct RankedHit {
+    payload: HashMap<String, Value>,
+}
+
+impl RankedHit {
+    fn document_key(&self) -> String {
+        let field = |name: &str| {
+            self.payload
+                .get(name)
+                .map(|v| v.to_string())
+                .unwrap_or_default()
+        };
+
+        format!("{}:{}:{}", field("path"), field("row_start"), field("row_end"))
+    }
+}
+
+// `score = sum over lists of 1 / (k + rank)`, rank being the hit's 1-based position in that
+// list; documents absent from a list simply contribute nothing for it. A free function (rather
+// than a method on `MyAgent`) so the rank math can be unit tested without a live Qdrant client.
+fn reciprocal_rank_fusion(dense: &[RankedHit], keyword: &[RankedHit], k: u32, limit: usize) -> Vec<RankedHit> {
+    let k = k as f64;
+    let mut scores: HashMap<String, f64> = HashMap::new();
+    let mut payloads: HashMap<String, HashMap<String, Value>> = HashMap::new();
+
+    for (list_rank, hit) in dense.iter().enumerate() {
+        let key = hit.document_key();
+        *scores.entry(key.clone()).or_insert(0.0) += 1.0 / (k + (list_rank + 1) as f64);
+        payloads.entry(key).or_insert_with(|| hit.payload.clone());
+    }
+
+    for (list_rank, hit) in keyword.iter().enumerate() {
+        let key = hit.document_key();
+        *scores.entry(key.clone()).or_insert(0.0) += 1.0 / (k + (list_rank + 1) as f64);
+        payloads.entry(key).or_insert_with(|| hit.payload.clone());
+    }
+
+    let mut fused: Vec<(String, f64)> = scores.into_iter().collect();
+    // Ties (e.g. two documents each ranked first in their own list) would otherwise keep
+    // `HashMap` iteration order, which is randomized per run - break ties on `document_key` so
+    // equal-score results come back in a stable order.
+    fused.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+
+    fused
+        .into_iter()
+        .take(limit)
+        .filter_map(|(key, _)| payloads.remove(&key).map(|payload| RankedHit { payload }))
+        .collect()
+}
+
+fn chunk_to_point(chunk: &Chunk, embedding: Vec<f32>) -> PointStruct {
+    let payload: Payload = serde_json::json!({
+        "path": chunk.path,
+        "row_start": chunk.row_start,
+        "row_end": chunk.row_end,
+        "contents": chunk.contents,
+    })
+    .try_into()
+    .unwrap();
+
+    PointStruct::new(uuid::Uuid::new_v4().to_string(), embedding, payload)
+}
+
+fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(path: &str, row_start: i64, row_end: i64) -> RankedHit {
+        let payload: Payload = serde_json::json!({
+            "path": path,
+            "row_start": row_start,
+            "row_end": row_end,
+            "contents": "irrelevant",
+        })
+        .try_into()
+        .unwrap();
+
+        RankedHit { payload: payload.into() }
+    }
+
+    #[test]
+    fn fusion_ranks_documents_found_in_both_lists_above_single_list_hits() {
+        let shared = hit("a.csv", 0, 1);
+        let dense_only = hit("b.csv", 0, 1);
+        let keyword_only = hit("c.csv", 0, 1);
+
+        let shared_key = shared.document_key();
+        let dense_only_key = dense_only.document_key();
+        let keyword_only_key = keyword_only.document_key();
+
+        let dense = vec![shared.clone(), dense_only];
+        let keyword = vec![shared, keyword_only];
+
+        let fused = reciprocal_rank_fusion(&dense, &keyword, 60, 10);
+        let keys: Vec<String> = fused.iter().map(|h| h.document_key()).collect();
+
+        assert_eq!(keys[0], shared_key);
+        assert!(keys.contains(&dense_only_key));
+        assert!(keys.contains(&keyword_only_key));
+    }
+
+    #[test]
+    fn fusion_respects_the_result_limit() {
+        let dense = vec![hit("a.csv", 0, 1), hit("b.csv", 0, 1), hit("c.csv", 0, 1)];
+        let keyword = vec![];
+
+        let fused = reciprocal_rank_fusion(&dense, &keyword, 60, 2);
+
+        assert_eq!(fused.len(), 2);
+    }
+
+    #[test]
+    fn fusion_includes_documents_absent_from_one_list() {
+        let dense = vec![hit("a.csv", 0, 1)];
+        let keyword = vec![hit("b.csv", 0, 1)];
+
+        let fused = reciprocal_rank_fusion(&dense, &keyword, 60, 10);
+        let keys: Vec<String> = fused.iter().map(|h| h.document_key()).collect();
+
+        assert_eq!(keys.len(), 2);
+        assert!(keys.contains(&dense[0].document_key()));
+        assert!(keys.contains(&keyword[0].document_key()));
+    }
+
+    #[test]
+    fn fusion_breaks_equal_scores_deterministically_by_document_key() {
+        // Both hits rank first in their own list, so they tie on fused score; the result order
+        // must still be deterministic (by `document_key`) rather than HashMap iteration order.
+        let first = hit("a.csv", 0, 1);
+        let second = hit("b.csv", 0, 1);
+
+        let first_key = first.document_key();
+        let second_key = second.document_key();
+        assert!(first_key < second_key);
+
+        let dense = vec![first];
+        let keyword = vec![second];
+
+        let fused_once = reciprocal_rank_fusion(&dense, &keyword, 60, 10);
+        let fused_again = reciprocal_rank_fusion(&dense, &keyword, 60, 10);
+
+        let keys_once: Vec<String> = fused_once.iter().map(|h| h.document_key()).collect();
+        let keys_again: Vec<String> = fused_again.iter().map(|h| h.document_key()).collect();
+
+        assert_eq!(keys_once, vec![first_key, second_key]);
+        assert_eq!(keys_once, keys_again);
+    }
 }
\ No newline at end of file
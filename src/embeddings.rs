@@ -0,0 +1,124 @@
+// Embedding providers
+// MyAgent talks to whatever implements `EmbeddingProvider` rather than being hardwired to
+// OpenAI, so users who don't want to ship their data to OpenAI (or don't have an API key) can
+// run fully local embeddings through e.g. Ollama instead.
+use anyhow::Result;
+use async_openai::types::{CreateEmbeddingRequest, EmbeddingInput};
+use async_openai::Embeddings as OpenAiEmbeddings;
+use async_openai::{config::OpenAIConfig, Client as OpenAIClient};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    async fn embed(&self, inputs: Vec<String>) -> Result<Vec<Vec<f32>>>;
+
+    // Size of the vectors this provider produces, used to size the Qdrant collection.
+    fn dimensions(&self) -> u64;
+}
+
+pub struct OpenAiEmbedder {
+    client: OpenAIClient<OpenAIConfig>,
+    model: String,
+    dimensions: u64,
+}
+
+impl OpenAiEmbedder {
+    pub fn new(client: OpenAIClient<OpenAIConfig>) -> Self {
+        Self {
+            client,
+            model: "text-embedding-ada-002".to_string(),
+            dimensions: 1536,
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAiEmbedder {
+    async fn embed(&self, inputs: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let request = CreateEmbeddingRequest {
+            model: self.model.clone(),
+            input: EmbeddingInput::StringArray(inputs),
+            user: None,
+            // `dimensions` is only accepted by the `text-embedding-3-*` family - ada-002 (our
+            // default model) rejects the request if it's set, so leave it unset here.
+            ..Default::default()
+        };
+
+        let mut data = OpenAiEmbeddings::new(&self.client).create(request).await?.data;
+
+        // The API doesn't guarantee `data` comes back in request order, and callers zip these
+        // embeddings positionally against their input texts - an out-of-order response would
+        // silently attach the wrong vector to each text.
+        data.sort_by_key(|d| d.index);
+
+        Ok(data.into_iter().map(|d| d.embedding).collect())
+    }
+
+    fn dimensions(&self) -> u64 {
+        self.dimensions
+    }
+}
+
+// Talks to a local Ollama instance's `/api/embeddings` endpoint, e.g. for `nomic-embed-text`,
+// so embeddings can run fully self-hosted without an OpenAI key.
+pub struct OllamaEmbedder {
+    http_client: reqwest::Client,
+    base_url: String,
+    model: String,
+    dimensions: u64,
+}
+
+#[derive(Serialize)]
+struct OllamaEmbeddingRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+}
+
+#[derive(Deserialize)]
+struct OllamaEmbeddingResponse {
+    embedding: Vec<f32>,
+}
+
+impl OllamaEmbedder {
+    // `dimensions` has to be supplied up front since Ollama doesn't report it - it's a property
+    // of whichever model is loaded (e.g. 768 for `nomic-embed-text`).
+    pub fn new(base_url: impl Into<String>, model: impl Into<String>, dimensions: u64) -> Self {
+        Self {
+            http_client: reqwest::Client::new(),
+            base_url: base_url.into(),
+            model: model.into(),
+            dimensions,
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OllamaEmbedder {
+    async fn embed(&self, inputs: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(inputs.len());
+
+        for input in &inputs {
+            let response = self
+                .http_client
+                .post(format!("{}/api/embeddings", self.base_url))
+                .json(&OllamaEmbeddingRequest {
+                    model: &self.model,
+                    prompt: input,
+                })
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<OllamaEmbeddingResponse>()
+                .await?;
+
+            embeddings.push(response.embedding);
+        }
+
+        Ok(embeddings)
+    }
+
+    fn dimensions(&self) -> u64 {
+        self.dimensions
+    }
+}
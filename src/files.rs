@@ -1,38 +1,194 @@
 /**
     File parsing and embedding into Qdrant
-    Next, we will implement a File struct for CSV file parsing - it should be able to hold the file path,
-    contents as well as the rows as a Vec<String> (string array, or more accurately a vector of strings).
-    There's a few reasons why we store the rows as a Vec<String>:
-    1 - Smaller chunks improve the retrieval accuracy, one of the biggest challenges that RAG has to deal
-        with. Retrieving a wrong or otherwise inaccurate document can hamper accuracy significantly.
-    2 - Improved retrieval accuracy leads to enhanced contextual relevance - which is quite important for
-        complex queries that require specific question.
-    3 - Processing and indexing smaller chunks
+    A File holds the file path and raw contents, plus a set of Chunks produced by parsing the
+    CSV and grouping its records into token-budgeted, overlapping slices. Smaller, self-describing
+    chunks (each carrying the CSV header plus its own row range) improve retrieval accuracy and
+    keep every embedded piece comfortably under the embedding model's token limit.
+
+    Rows aren't split on bare `\n` any more, since a single CSV cell can legitimately contain an
+    embedded newline (or comma) inside a quoted field - splitting on `\n` would cut such a record
+    in half. Instead we parse proper CSV records and group them into chunks bounded by an
+    approximate token budget, carrying the CSV header into every chunk so each one is
+    self-describing.
 */
 use anyhow::Result;
+use csv::{ReaderBuilder, StringRecord, Terminator, WriterBuilder};
 use std::path::PathBuf;
 
+// ~4 characters per token is the usual rule of thumb for English text; good enough for sizing
+// chunks without pulling in a real tokenizer.
+const DEFAULT_TOKEN_BUDGET: usize = 400;
+// Number of trailing rows from a chunk that get repeated at the start of the next one, so a
+// retrieved chunk doesn't lose the row immediately before its first one.
+const DEFAULT_CHUNK_OVERLAP_ROWS: usize = 1;
+
 pub struct File {
     pub path: String,
     pub contents: String,
-    pub rows: Vec<String>,
+    pub chunks: Vec<Chunk>,
+}
+
+// A self-describing slice of a CSV file: the header plus a run of data rows, along with enough
+// location metadata (`path`, `row_start`, `row_end`) to cite the retrieved context back to an
+// exact range in the original file.
+#[derive(Clone)]
+pub struct Chunk {
+    pub path: String,
+    pub row_start: usize,
+    pub row_end: usize,
+    pub contents: String,
 }
 
 impl File {
     pub fn new(path: PathBuf) -> Result<Self> {
-        let contents = std::fs::read_to_string(&path)?;
+        Self::with_chunking(path, DEFAULT_TOKEN_BUDGET, DEFAULT_CHUNK_OVERLAP_ROWS)
+    }
 
+    pub fn with_chunking(path: PathBuf, token_budget: usize, overlap_rows: usize) -> Result<Self> {
+        let contents = std::fs::read_to_string(&path)?;
         let path_as_str = format!("{}", path.display());
 
-        let rows = contents
-            .lines()
-            .map(|x| x.to_owned())
-            .collect::<Vec<String>>();
+        let mut reader = ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(contents.as_bytes());
+
+        let header_line = record_to_line(reader.headers()?);
+
+        let rows = reader
+            .records()
+            .map(|record| record.map(|r| record_to_line(&r)))
+            .collect::<std::result::Result<Vec<String>, csv::Error>>()?;
 
-          Ok(Self {
+        let chunks = chunk_rows(&path_as_str, &header_line, &rows, token_budget, overlap_rows);
+
+        Ok(Self {
             path: path_as_str,
             contents,
-            rows
-          })
+            chunks,
+        })
+    }
+}
+
+fn chunk_rows(
+    path: &str,
+    header_line: &str,
+    rows: &[String],
+    token_budget: usize,
+    overlap_rows: usize,
+) -> Vec<Chunk> {
+    let header_tokens = approx_token_count(header_line);
+
+    let mut chunks = Vec::new();
+    let mut current_rows: Vec<String> = Vec::new();
+    let mut current_tokens = header_tokens;
+    let mut row_start = 0usize;
+
+    for (row_index, row) in rows.iter().enumerate() {
+        let row_tokens = approx_token_count(row);
+
+        if !current_rows.is_empty() && current_tokens + row_tokens > token_budget {
+            chunks.push(build_chunk(path, header_line, &current_rows, row_start, row_index - 1));
+
+            let overlap_start = current_rows.len().saturating_sub(overlap_rows);
+            let carried = current_rows.split_off(overlap_start);
+            row_start = row_index - carried.len();
+            current_tokens = header_tokens + carried.iter().map(|r| approx_token_count(r)).sum::<usize>();
+            current_rows = carried;
+        }
+
+        current_tokens += row_tokens;
+        current_rows.push(row.clone());
+    }
+
+    if !current_rows.is_empty() {
+        chunks.push(build_chunk(path, header_line, &current_rows, row_start, rows.len() - 1));
+    }
+
+    chunks
+}
+
+fn build_chunk(path: &str, header_line: &str, rows: &[String], row_start: usize, row_end: usize) -> Chunk {
+    let mut contents = String::from(header_line);
+    contents.push('\n');
+    contents.push_str(&rows.join("\n"));
+
+    Chunk {
+        path: path.to_string(),
+        row_start,
+        row_end,
+        contents,
     }
-}
\ No newline at end of file
+}
+
+fn approx_token_count(s: &str) -> usize {
+    (s.len() / 4).max(1)
+}
+
+// Re-serialises a parsed record into a single CSV line, so quoting is re-applied correctly for
+// fields that contain commas, quotes or embedded newlines.
+fn record_to_line(record: &StringRecord) -> String {
+    let mut writer = WriterBuilder::new()
+        .terminator(Terminator::Any(b'\n'))
+        .from_writer(vec![]);
+
+    writer.write_record(record).unwrap();
+
+    let bytes = writer.into_inner().unwrap();
+    String::from_utf8(bytes).unwrap().trim_end_matches('\n').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn chunk_rows_splits_once_the_token_budget_is_exceeded() {
+        let rows = rows(&["row1", "row2", "row3"]);
+
+        let chunks = chunk_rows("file.csv", "a,b", &rows, 3, 0);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].row_start, chunks[0].row_end), (0, 1));
+        assert_eq!((chunks[1].row_start, chunks[1].row_end), (2, 2));
+    }
+
+    #[test]
+    fn chunk_rows_carries_the_header_into_every_chunk() {
+        let rows = rows(&["row1", "row2", "row3"]);
+
+        let chunks = chunk_rows("file.csv", "a,b", &rows, 3, 0);
+
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(chunk.contents.starts_with("a,b\n"));
+        }
+    }
+
+    #[test]
+    fn chunk_rows_repeats_overlap_rows_across_the_chunk_boundary() {
+        let rows = rows(&["1,a", "2,b", "3,c", "4,d"]);
+
+        let chunks = chunk_rows("file.csv", "id,name", &rows, 3, 1);
+
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].row_end, chunks[1].row_start);
+        assert_eq!(chunks[1].row_end, chunks[2].row_start);
+    }
+
+    #[test]
+    fn chunk_rows_covers_every_row_with_no_overlap() {
+        let rows = rows(&["0", "1", "2", "3", "4", "5"]);
+
+        let chunks = chunk_rows("file.csv", "id", &rows, 5, 0);
+
+        assert_eq!(chunks.first().unwrap().row_start, 0);
+        assert_eq!(chunks.last().unwrap().row_end, rows.len() - 1);
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[1].row_start, pair[0].row_end + 1);
+        }
+    }
+}
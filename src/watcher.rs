@@ -0,0 +1,45 @@
+// Low-level directory watching glue for `MyAgent::watch` - translates filesystem events from
+// `notify` into the handful of changes the ingestion pipeline cares about (upsert or remove),
+// so the agent-side code doesn't need to know anything about `notify`'s event shapes.
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+
+use anyhow::Result;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+pub enum Change {
+    Upsert(PathBuf),
+    Remove(PathBuf),
+}
+
+// Watches `path` recursively. The returned `RecommendedWatcher` must be kept alive for as long
+// as you want to keep watching; the `Receiver` yields raw filesystem events as they arrive.
+pub fn start_watching(path: &Path) -> Result<(RecommendedWatcher, Receiver<notify::Result<Event>>)> {
+    let (tx, rx) = channel();
+
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(path, RecursiveMode::Recursive)?;
+
+    Ok((watcher, rx))
+}
+
+pub fn changes_from_event(event: Event) -> Vec<Change> {
+    match event.kind {
+        EventKind::Remove(_) => event.paths.into_iter().map(Change::Remove).collect(),
+        EventKind::Create(_) | EventKind::Modify(_) => event
+            .paths
+            .into_iter()
+            .filter_map(|path| {
+                if path.is_file() {
+                    Some(Change::Upsert(path))
+                } else if !path.exists() {
+                    // A rename away from `path` arrives as a modify event for a path that is gone.
+                    Some(Change::Remove(path))
+                } else {
+                    None
+                }
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}